@@ -0,0 +1,338 @@
+use allocator::{AllocError, AllocResult, BaseAllocator, PageAllocator};
+
+/// 经典的buddy system页分配器：按阶(order)组织空闲链表，order k的链表里每个块大小是
+/// `2^k`页。申请时找满足大小/对齐的最小阶，不够就从更大的阶里一路二等分下来(多出来的一半
+/// 挂回低阶链表)；释放时反过来，不断找"buddy"块，如果buddy也空闲就合并成更大的块，
+/// 直到不能再合并或者到了MAX_ORDER，这样空闲内存会尽量聚成大块，不容易碎片化。
+///
+/// 空闲链表同样是嵌入式的：每个空闲块的前一个usize存着"同一阶下一个空闲块的地址"，不需要
+/// 额外的位图/数组。
+///
+/// 分配出去的块不能再用"num_pages算出来的阶"反推它实际占了几页：align_pow2可能比size本身
+/// 要求更高的阶(比如只要1页但要求2页对齐，实际会切下一个2页的块)，这种情况下dealloc_pages
+/// 只拿得到num_pages，算出来的阶会比当初真正分配的阶小，导致多出来的那部分页永远还不回去、
+/// used_pages也会一直偏高。
+///
+/// 之前这里用一张固定256项的(地址, 阶)表记录，表满了就只能退化成按num_pages估算——正常
+/// 使用下这张表会被普通分配迅速填满(不管alignment有没有撑大过阶)，之后任何alignment撑大
+/// 过阶的分配一释放就会查不到真实阶，永久漏记页数。现在改成按页直接索引的`block_order`
+/// 数组，每一页都有自己的槽位记它所属块分配时的阶，不用扫描也没有表满了记不住的问题：
+/// 简化处理，数组大小由`MAX_PAGES`这个常量泛型参数固定，调用者需要保证总页数不超过它。
+pub struct BuddyPageAllocator<const PAGE_SIZE: usize, const MAX_ORDER: usize, const MAX_PAGES: usize> {
+    regions: [Region; MAX_REGIONS],
+    region_count: usize,
+    total_pages: usize,
+    used_pages: usize,
+    free_lists: [usize; MAX_ORDER],
+    /// 按页号(相对第一个region的起始地址)直接索引，记录每一页所属块分配时实际用的阶；
+    /// 只有块起始页的槽位有意义(块内其它页不会被单独拿来dealloc)。
+    block_order: [u8; MAX_PAGES],
+}
+
+/// init()/add_memory()注册进来的一块连续内存区域，和lib.rs里EarlyAllocator的Region是
+/// 同样的思路：buddy_of()算buddy地址时必须只在同一个region内部做异或，不然两块本来就
+/// 不连续的内存会被错误地认成彼此的buddy，从而被错误合并。
+#[derive(Clone, Copy)]
+struct Region {
+    start: usize,
+    end: usize, // [start, end)
+}
+
+/// 最多能同时掌管几块内存区域，写死一个够用的数量，不用动态分配
+const MAX_REGIONS: usize = 4;
+
+impl<const PAGE_SIZE: usize, const MAX_ORDER: usize, const MAX_PAGES: usize>
+    BuddyPageAllocator<PAGE_SIZE, MAX_ORDER, MAX_PAGES>
+{
+    const EMPTY_REGION: Region = Region { start: 0, end: 0 };
+
+    pub const fn new() -> Self {
+        Self {
+            regions: [Self::EMPTY_REGION; MAX_REGIONS],
+            region_count: 0,
+            total_pages: 0,
+            used_pages: 0,
+            free_lists: [0; MAX_ORDER],
+            block_order: [0; MAX_PAGES],
+        }
+    }
+
+    fn block_size(order: usize) -> usize {
+        (1usize << order) * PAGE_SIZE
+    }
+
+    /// 满足num_pages页所需要的最小阶
+    fn order_for_pages(num_pages: usize) -> usize {
+        let mut order = 0;
+        while (1usize << order) < num_pages {
+            order += 1;
+        }
+        order
+    }
+
+    /// addr相对第一个region起始地址的页号，用来索引block_order。简化处理：要求addr落在
+    /// base..base + MAX_PAGES*PAGE_SIZE范围内，由调用者保证总页数不超过MAX_PAGES。
+    fn page_index(&self, addr: usize) -> usize {
+        (addr - self.regions[0].start) / PAGE_SIZE
+    }
+
+    fn record_order(&mut self, addr: usize, order: usize) {
+        let idx = self.page_index(addr);
+        self.block_order[idx] = order as u8;
+    }
+
+    fn take_order(&self, addr: usize) -> usize {
+        let idx = self.page_index(addr);
+        self.block_order[idx] as usize
+    }
+
+    /// addr落在哪个region里，找不到说明用法不对(addr不是之前alloc_pages给出来的)
+    fn region_of(&self, addr: usize) -> Option<&Region> {
+        self.regions[..self.region_count]
+            .iter()
+            .find(|r| addr >= r.start && addr < r.end)
+    }
+
+    fn next_of(addr: usize) -> usize {
+        unsafe { *(addr as *const usize) }
+    }
+
+    fn set_next(addr: usize, next: usize) {
+        unsafe { *(addr as *mut usize) = next };
+    }
+
+    fn push_free(&mut self, order: usize, addr: usize) {
+        Self::set_next(addr, self.free_lists[order]);
+        self.free_lists[order] = addr;
+    }
+
+    fn pop_free(&mut self, order: usize) -> Option<usize> {
+        let head = self.free_lists[order];
+        if head == 0 {
+            return None;
+        }
+        self.free_lists[order] = Self::next_of(head);
+        Some(head)
+    }
+
+    /// 在order这一阶的空闲链表里找到地址为addr的块并摘掉，找到返回true(合并的时候用)
+    fn take_free(&mut self, order: usize, addr: usize) -> bool {
+        let mut cur = self.free_lists[order];
+        let mut prev = 0usize;
+        while cur != 0 {
+            if cur == addr {
+                if prev == 0 {
+                    self.free_lists[order] = Self::next_of(cur);
+                } else {
+                    Self::set_next(prev, Self::next_of(cur));
+                }
+                return true;
+            }
+            prev = cur;
+            cur = Self::next_of(cur);
+        }
+        false
+    }
+
+    /// 算addr这个块在order阶下的buddy地址，只在addr所属的region内部做异或：如果算出来的
+    /// buddy超出了这个region的范围，说明紧挨着的其实是另一块不相关的内存(或者压根没有)，
+    /// 不能合并，返回None。
+    fn buddy_of(&self, addr: usize, order: usize) -> Option<usize> {
+        let region = self.region_of(addr)?;
+        let offset = addr - region.start;
+        let buddy = region.start + (offset ^ Self::block_size(order));
+        if buddy >= region.start && buddy + Self::block_size(order) <= region.end {
+            Some(buddy)
+        } else {
+            None
+        }
+    }
+
+    /// 把region(start..start+size)里能放下的、2的幂页数的块都挂到free_lists上，从高阶往低阶贪心切。
+    /// 简化处理：不要求size正好是2^MAX_ORDER页，剩下不够凑成MAX_ORDER大小的部分按次高阶继续切。
+    fn add_region(&mut self, start: usize, size: usize) {
+        self.regions[self.region_count] = Region { start, end: start + size };
+        self.region_count += 1;
+
+        let mut addr = start;
+        let mut remaining = size / PAGE_SIZE;
+        let mut order = MAX_ORDER - 1;
+        while remaining > 0 {
+            while (1usize << order) > remaining && order > 0 {
+                order -= 1;
+            }
+            self.push_free(order, addr);
+            addr += Self::block_size(order);
+            remaining -= 1usize << order;
+        }
+    }
+}
+
+impl<const PAGE_SIZE: usize, const MAX_ORDER: usize, const MAX_PAGES: usize> BaseAllocator
+    for BuddyPageAllocator<PAGE_SIZE, MAX_ORDER, MAX_PAGES>
+{
+    fn init(&mut self, start: usize, size: usize) {
+        self.total_pages = size / PAGE_SIZE;
+        self.used_pages = 0;
+        self.add_region(start, size);
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        if self.region_count >= MAX_REGIONS {
+            return Err(AllocError::NoMemory);
+        }
+        self.total_pages += size / PAGE_SIZE;
+        self.add_region(start, size);
+        Ok(())
+    }
+}
+
+impl<const PAGE_SIZE: usize, const MAX_ORDER: usize, const MAX_PAGES: usize> PageAllocator
+    for BuddyPageAllocator<PAGE_SIZE, MAX_ORDER, MAX_PAGES>
+{
+    const PAGE_SIZE: usize = PAGE_SIZE;
+
+    fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        let size_order = Self::order_for_pages(num_pages);
+        // 按对齐要求需要的阶数：align_pow2是字节的幂，简化处理只按"页数对齐"考虑
+        let align_order = if align_pow2 > Self::PAGE_SIZE.trailing_zeros() as usize {
+            align_pow2 - Self::PAGE_SIZE.trailing_zeros() as usize
+        } else {
+            0
+        };
+        let order = size_order.max(align_order);
+        if order >= MAX_ORDER {
+            return Err(AllocError::NoMemory);
+        }
+
+        let mut cur_order = order;
+        while cur_order < MAX_ORDER && self.free_lists[cur_order] == 0 {
+            cur_order += 1;
+        }
+        if cur_order == MAX_ORDER {
+            return Err(AllocError::NoMemory);
+        }
+
+        let block = self.pop_free(cur_order).unwrap();
+        // 比需要的阶大，就不断二等分，前一半继续切，后一半(buddy)挂回低一阶的空闲链表
+        while cur_order > order {
+            cur_order -= 1;
+            let buddy = block + Self::block_size(cur_order);
+            self.push_free(cur_order, buddy);
+        }
+
+        self.used_pages += 1usize << order;
+        self.record_order(block, order);
+        Ok(block)
+    }
+
+    fn dealloc_pages(&mut self, pos: usize, _num_pages: usize) {
+        // 用alloc_pages时记下的真实阶，这样align_pow2把阶撑大的情况也能正确合并/计数；
+        // block_order是按页直接索引的数组，不存在"表满了查不到"的情况，不需要再退化成
+        // 按num_pages估算。
+        let mut order = self.take_order(pos);
+        let mut addr = pos;
+        self.used_pages -= 1usize << order;
+
+        while order + 1 < MAX_ORDER {
+            match self.buddy_of(addr, order) {
+                Some(buddy) if self.take_free(order, buddy) => {
+                    addr = addr.min(buddy);
+                    order += 1;
+                }
+                _ => break,
+            }
+        }
+        self.push_free(order, addr);
+    }
+
+    fn total_pages(&self) -> usize {
+        self.total_pages
+    }
+
+    fn used_pages(&self) -> usize {
+        self.used_pages
+    }
+
+    fn available_pages(&self) -> usize {
+        self.total_pages - self.used_pages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_SIZE: usize = 4096;
+    type TestAllocator = BuddyPageAllocator<PAGE_SIZE, 8, 256>;
+
+    fn new_allocator(mem: &mut [u8]) -> TestAllocator {
+        let mut a = TestAllocator::new();
+        a.init(mem.as_mut_ptr() as usize, mem.len());
+        a
+    }
+
+    #[test]
+    fn alloc_dealloc_roundtrip() {
+        let mut mem = [0u8; 64 * PAGE_SIZE];
+        let mut a = new_allocator(&mut mem);
+        assert_eq!(a.used_pages(), 0);
+
+        let addr = a.alloc_pages(1, 0).unwrap();
+        assert_eq!(a.used_pages(), 1);
+        a.dealloc_pages(addr, 1);
+        assert_eq!(a.used_pages(), 0);
+        assert_eq!(a.available_pages(), a.total_pages());
+    }
+
+    /// 复现review指出的bug：alignment把实际分配的阶撑大到超过num_pages本身需要的阶，
+    /// dealloc_pages必须按真实阶(而不是按num_pages重新估算)归还页数，否则used_pages
+    /// 会永久多算，可用页越来越少。先分配4个order-0的块，再测被alignment撑大阶的那次分配，
+    /// 最后全部释放，确认used_pages回到0。
+    #[test]
+    fn dealloc_uses_real_order_after_alignment_widens_it() {
+        let mut mem = [0u8; 64 * PAGE_SIZE];
+        let mut a = new_allocator(&mut mem);
+
+        let mut addrs = [0usize; 4];
+        for slot in addrs.iter_mut() {
+            *slot = a.alloc_pages(1, 0).unwrap();
+        }
+
+        // 只要1页，但按2页对齐，实际会切出一个2页(order 1)的块
+        let align_pow2 = (2 * PAGE_SIZE).trailing_zeros() as usize;
+        let addr = a.alloc_pages(1, align_pow2).unwrap();
+        let used_before_free = a.used_pages();
+
+        a.dealloc_pages(addr, 1);
+        // 真实分配的是2页，释放后used_pages应该正好少2，而不是只少1
+        assert_eq!(a.used_pages(), used_before_free - 2);
+
+        for addr in addrs {
+            a.dealloc_pages(addr, 1);
+        }
+        assert_eq!(a.used_pages(), 0);
+        assert_eq!(a.available_pages(), a.total_pages());
+    }
+
+    #[test]
+    fn buddies_coalesce_back_into_one_block() {
+        let mut mem = [0u8; 8 * PAGE_SIZE];
+        let mut a = new_allocator(&mut mem);
+
+        let a0 = a.alloc_pages(1, 0).unwrap();
+        let a1 = a.alloc_pages(1, 0).unwrap();
+        let a2 = a.alloc_pages(1, 0).unwrap();
+        let a3 = a.alloc_pages(1, 0).unwrap();
+        assert_eq!(a.used_pages(), 4);
+
+        a.dealloc_pages(a0, 1);
+        a.dealloc_pages(a1, 1);
+        a.dealloc_pages(a2, 1);
+        a.dealloc_pages(a3, 1);
+        assert_eq!(a.used_pages(), 0);
+
+        // 全部释放、两两合并之后，应该又能一次性分配出一个4页的大块
+        assert!(a.alloc_pages(4, 0).is_ok());
+    }
+}
@@ -0,0 +1,150 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// 一条分配事件记录。id单调递增，加上checksum，万一seqlock的保护出了差错，读的人还能
+/// 再靠id是否连续、checksum是否对得上来兜底判断这条记录有没有问题。
+#[derive(Clone, Copy, Debug)]
+pub struct AllocEvent {
+    pub id: u64,
+    pub kind: EventKind,
+    /// 没有接真正的时钟源，这里用一个单调递增的计数器代替时间戳，只保证先后顺序对
+    pub timestamp: u64,
+    pub size: usize,
+    pub align: usize,
+    pub addr: usize,
+    /// 当前task/pid，这个crate里拿不到任务信息，先固定填0(表示"不可用")
+    pub task_id: u64,
+    checksum: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    AllocBytes,
+    DeallocBytes,
+    AllocPages,
+    DeallocPages,
+}
+
+impl AllocEvent {
+    fn new(id: u64, kind: EventKind, timestamp: u64, size: usize, align: usize, addr: usize, task_id: u64) -> Self {
+        let mut ev = Self { id, kind, timestamp, size, align, addr, task_id, checksum: 0 };
+        ev.checksum = ev.compute_checksum();
+        ev
+    }
+
+    fn compute_checksum(&self) -> u64 {
+        self.id
+            .wrapping_mul(31)
+            .wrapping_add(self.kind as u64)
+            .wrapping_mul(31)
+            .wrapping_add(self.timestamp)
+            .wrapping_mul(31)
+            .wrapping_add(self.size as u64)
+            .wrapping_mul(31)
+            .wrapping_add(self.align as u64)
+            .wrapping_mul(31)
+            .wrapping_add(self.addr as u64)
+            .wrapping_mul(31)
+            .wrapping_add(self.task_id)
+    }
+
+    /// 记录有没有被破坏(兜底检查，正常情况下seqlock已经保证读不到半写的记录了)
+    pub fn is_valid(&self) -> bool {
+        self.checksum == self.compute_checksum()
+    }
+}
+
+/// 环形缓冲区里的一个槽位，用经典的seqlock保护：seq是偶数表示"当前没有写者、数据完整可读"，
+/// 奇数表示"正有写者在写这个槽位"。写者：先把seq变成奇数，写完数据后用Release store把seq
+/// 变成偶数；读者：Acquire读seq，如果是奇数(正在写)或者读完数据后seq变了(读的过程中被重写)，
+/// 就丢弃这次读到的数据。这样能保证读者看到的要么是完整的一次写入，要么干脆不读，不会有
+/// "读了一半"这种在Rust内存模型下本来就是UB的情况——裸指针读写如果没有这层同步，在弱内存序
+/// 的目标(riscv64/aarch64)上即使checksum对上了也不能排除读者先看到seq更新、后看到数据更新
+/// 这种重排。
+struct Slot {
+    seq: AtomicUsize,
+    event: UnsafeCell<AllocEvent>,
+}
+
+// SAFETY: event字段的访问完全由seq这个原子量的偶/奇状态和Acquire/Release顺序控制，
+// 不会有两个核同时不受控制地读写同一个event。
+unsafe impl Sync for Slot {}
+
+/// 固定容量的环形缓冲区，记录每一次分配器操作，用来事后排查allocator的bug/内存泄漏。
+/// 槽位下标用`id % CAP`算，CAP取2的幂(比如下面的1024)。
+pub struct EventLog<const CAP: usize> {
+    slots: [Slot; CAP],
+    next_id: AtomicU64,
+}
+
+impl<const CAP: usize> EventLog<CAP> {
+    const EMPTY_EVENT: AllocEvent = AllocEvent {
+        id: 0,
+        kind: EventKind::AllocBytes,
+        timestamp: 0,
+        size: 0,
+        align: 0,
+        addr: 0,
+        task_id: 0,
+        checksum: 0,
+    };
+
+    const EMPTY_SLOT: Slot = Slot {
+        seq: AtomicUsize::new(0),
+        event: UnsafeCell::new(Self::EMPTY_EVENT),
+    };
+
+    pub const fn new() -> Self {
+        Self {
+            slots: [Self::EMPTY_SLOT; CAP],
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// 记一条事件。id只靠fetch_add拿，保证每次调用分到的id互不相同；槽位由id % CAP决定，
+    /// 写槽位本身用上面说的seqlock协议保护，发布给其它核用Release，配合iter()里的Acquire。
+    pub fn record(&self, kind: EventKind, size: usize, align: usize, addr: usize, task_id: u64) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let pos = (id as usize) % CAP;
+        let event = AllocEvent::new(id, kind, id, size, align, addr, task_id);
+        let slot = &self.slots[pos];
+
+        let seq = slot.seq.load(Ordering::Relaxed);
+        // 进入写入状态：变成奇数，读者看到奇数就知道这次不能读，要跳过
+        slot.seq.store(seq.wrapping_add(1), Ordering::Release);
+        unsafe {
+            *slot.event.get() = event;
+        }
+        // 写完，变回偶数发布出去；Release保证上面对event的写入不会被重排到这次store之后，
+        // 读者用Acquire读到这个偶数值时，也一定能看到event的最新内容
+        slot.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+
+    /// 按写入先后顺序遍历当前缓冲区里还留着的记录；正在被写、或者读的过程中被覆盖写的
+    /// 槽位会被跳过(seqlock检测到)，checksum再兜底检查一次。
+    pub fn iter(&self) -> impl Iterator<Item = AllocEvent> + '_ {
+        let written = self.next_id.load(Ordering::Acquire) - 1;
+        // 已经发出去的id是1..=written，缓冲区里还留着的是其中最后CAP个
+        let start = written.saturating_sub(CAP as u64) + 1;
+        (start..=written).filter_map(move |id| {
+            let pos = (id as usize) % CAP;
+            let slot = &self.slots[pos];
+
+            let seq1 = slot.seq.load(Ordering::Acquire);
+            if seq1 % 2 != 0 {
+                return None; // 正在被写，跳过
+            }
+            let event = unsafe { *slot.event.get() };
+            let seq2 = slot.seq.load(Ordering::Acquire);
+            if seq1 != seq2 {
+                return None; // 读的过程中被重写了，数据可能新旧夹杂，丢弃
+            }
+
+            // 槽位里已经是别的id(被后来的记录覆盖了，或者这个id的写者还没写进来)也跳过
+            if event.is_valid() && event.id == id { Some(event) } else { None }
+        })
+    }
+}
+
+/// 全局事件日志，容量1024条，够排查一次短时间的分配风暴了
+pub static EVENT_LOG: EventLog<1024> = EventLog::new();
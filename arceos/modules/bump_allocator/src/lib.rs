@@ -1,9 +1,30 @@
-#![no_std]
+// 和arceos/ulib/axstd/src/lib.rs一样，test cfg下不开no_std，这样buddy.rs/slab.rs里的
+// #[cfg(test)]单测能用std给分配器搭测试用的内存/容器
+#![cfg_attr(not(test), no_std)]
 
 use core::ptr::NonNull;
 
 use allocator::{BaseAllocator, ByteAllocator, PageAllocator};
 
+mod slab;
+pub use slab::SlabByteAllocator;
+
+mod buddy;
+pub use buddy::BuddyPageAllocator;
+
+/// smp feature打开时，在SlabByteAllocator前面挂一层per-CPU array cache，见percpu.rs。
+#[cfg(feature = "smp")]
+mod percpu;
+#[cfg(feature = "smp")]
+pub use percpu::SmpSlabByteAllocator;
+
+/// alloc-log feature打开时，把每次分配/回收操作都记一条事件到一个环形缓冲区里，
+/// 方便事后排查allocator的bug/内存泄漏，见eventlog.rs。
+#[cfg(feature = "alloc-log")]
+mod eventlog;
+#[cfg(feature = "alloc-log")]
+pub use eventlog::{AllocEvent, EventKind, EVENT_LOG};
+
 /// Early memory allocator
 /// Use it before formal bytes-allocator and pages-allocator can work!
 /// This is a double-end memory range:
@@ -33,34 +54,62 @@ use allocator::{BaseAllocator, ByteAllocator, PageAllocator};
 /// PageAllocator:
 ///     按上面的注释"For pages area, it will never be freed!"，不考虑页的回收
 /// 
-/// EarlyAllocator所掌管的内存区域是init时注册给它的，不考虑add_memory()再增加可分配内存区域的情况：
-/// 这第三个练习`make run A=exercises/alt_alloc/`，是arceos/modules/alt_axalloc/src/lib.rs里的static GLOBAL_ALLOCATOR
-/// 需要初始化一个EarlyAllocator用来实现内存分配，这才用到EarlyAllocator，但是GLOBAL_ALLOCATOR对外提供的add_memory()是unimplemented!()，
-/// 所以这里add_memory()也不用实现。
-/// 
+/// EarlyAllocator所掌管的内存区域最初是init时注册给它的：这第三个练习`make run A=exercises/alt_alloc/`，
+/// 是arceos/modules/alt_axalloc/src/lib.rs里的static GLOBAL_ALLOCATOR需要初始化一个EarlyAllocator
+/// 用来实现内存分配，这才用到EarlyAllocator。add_memory()现在也实现了，见下面关于region数组的说明。
+///
 /// 很多报错情况没管，比如内存不够b_pos和p_pos相互越过的情况
 /// 
 /// 
 /// 这里bump_allocator正经做应该是可以用arceos/modules/bump_allocator/Cargo.toml的那个allocator依赖里的 BuddyByteAllocator
 /// 和 BitmapPageAllocator 组合出来的，依赖是写好在Cargo.toml里的，后面可以试试(TODO)。
+///
+/// add_memory()现在也实现了：EarlyAllocator不再只认init()时给的那一块区域，而是维护一个
+/// 小的region数组(MAX_REGIONS个)，每个region记着自己的[start, end)、b_pos、p_pos。
+/// alloc()/alloc_pages()按region数组的顺序试，当前region装不下就换下一个region，而不是像
+/// 之前那样任由b_pos/p_pos在同一个区域里相互越过。total_bytes/available_bytes/total_pages
+/// 这些统计量也都是把所有region加起来。
+///
+/// 现在已经把"后面可以试试"的那部分做了一半：slab.rs里的SlabByteAllocator是一个真正会按size class
+/// 复用对象的ByteAllocator实现(而不是count归零才整体回收)，可以和PageAllocator组合起来用。
+/// EarlyAllocator本身保持不变，继续给启动阶段用；SlabByteAllocator是可选的替代实现，
+/// 具体用哪个由上层(alt_axalloc)在初始化GLOBAL_ALLOCATOR时选择。
+///
+/// 另外加了alloc-log这个feature：打开后EarlyAllocator的alloc/dealloc/alloc_pages/dealloc_pages
+/// 都会往eventlog::EVENT_LOG这个环形缓冲区里记一条事件，事后可以用EVENT_LOG.iter()按顺序
+/// 捞出来看，排查allocator这边的bug/泄漏用。
+///
+/// buddy.rs里的BuddyPageAllocator是EarlyAllocator"For pages area, it will never be freed!"
+/// 这个限制的真正解法：dealloc_pages会找buddy块尝试合并，页是真的能回收复用的，
+/// 可以配合SlabByteAllocator当它的PageAllocator用。
 
-pub struct EarlyAllocator<const PAGE_SIZE: usize> { // 常量作为泛型参数
+/// init()或者add_memory()注册进来的一块连续内存区域，EarlyAllocator可以同时掌管好几块这样的区域。
+#[derive(Clone, Copy)]
+struct Region {
     start: usize,
-    end: usize, // 可供EarlyAllocator策划分配的区域为[start, end)
+    end: usize, // 本region可供分配的区域为[start, end)
     b_pos: usize, // [start, b_pos)是Byte分配
     p_pos: usize, // [p_pos, end)是Page分配
-    count: usize, // 现在分配的字节区域个数
+}
+
+/// 最多能同时掌管几块内存区域，写死一个够用的数量，不用动态分配
+const MAX_REGIONS: usize = 4;
+
+pub struct EarlyAllocator<const PAGE_SIZE: usize> { // 常量作为泛型参数
+    regions: [Region; MAX_REGIONS],
+    region_count: usize,
+    count: usize, // 现在分配的字节区域个数，所有region共用一个计数
 }
 
 impl<const PAGE_SIZE: usize> EarlyAllocator<PAGE_SIZE> {
+    const EMPTY_REGION: Region = Region { start: 0, end: 0, b_pos: 0, p_pos: 0 };
+
     /// 如果一个fn用来初始化一个 static变量 或者在 const fn 中使用它，则这个fn必须是 const fn，必须在编译期就能跑它。
     /// 在arceos/modules/alt_axalloc/src/lib.rs中，EarlyAllocator::new()在 const fn 中被调用，所以这里必须是 const fn
     pub const fn new() -> Self {
         Self {
-            start: 0,
-            end: 0,
-            b_pos: 0,
-            p_pos: 0,
+            regions: [Self::EMPTY_REGION; MAX_REGIONS],
+            region_count: 0,
             count: 0
         }
     }
@@ -68,45 +117,62 @@ impl<const PAGE_SIZE: usize> EarlyAllocator<PAGE_SIZE> {
 
 impl<const PAGE_SIZE: usize> BaseAllocator for EarlyAllocator<PAGE_SIZE> {
     fn init(&mut self, start: usize, size: usize) {
-        self.start = start;
-        self.end = start + size;
-        self.b_pos = start;
-        self.p_pos = self.end;
+        self.regions[0] = Region { start, end: start + size, b_pos: start, p_pos: start + size };
+        self.region_count = 1;
     }
 
-    /// 练习3，arceos/modules/alt_axalloc/src/lib.rs里的static GLOBAL_ALLOCATOR，add_memory()是unimplemented!()，所以这里也不用实现
+    /// 把新发现的一块空闲区域(比如多出来的一条内存bank)登记成一个新region，后续alloc会按顺序
+    /// 试到它。region数组满了就报错，而不是悄悄丢掉这块内存。
     fn add_memory(&mut self, start: usize, size: usize) -> allocator::AllocResult {
-        unimplemented!() // 这里为什么没写返回值能过编译？unimplemented!()会调用panic_handler返回never type (`!`)，`!`可以适配任何返回类型
+        if self.region_count >= MAX_REGIONS {
+            return Err(allocator::AllocError::NoMemory);
+        }
+        self.regions[self.region_count] = Region { start, end: start + size, b_pos: start, p_pos: start + size };
+        self.region_count += 1;
+        Ok(())
     }
 }
 
 impl<const PAGE_SIZE: usize> ByteAllocator for EarlyAllocator<PAGE_SIZE> {
     fn alloc(&mut self, layout: core::alloc::Layout) -> allocator::AllocResult<core::ptr::NonNull<u8>> {
         let align = layout.align();
-        self.b_pos = (self.b_pos + align - 1) & !(align - 1); // b_pos向上取整对齐后再分配，写(self.b_pos + align - 1) / align * align也行
-        let res = unsafe { NonNull::new_unchecked(self.b_pos as *mut u8) };
-        self.b_pos += layout.size();
-        self.count += 1;
-        Ok(res)
+        for region in self.regions[..self.region_count].iter_mut() {
+            let aligned = (region.b_pos + align - 1) & !(align - 1); // b_pos向上取整对齐后再分配
+            if aligned + layout.size() > region.p_pos {
+                continue; // 这个region装不下，换下一个region试，而不是让b_pos越过p_pos
+            }
+            region.b_pos = aligned + layout.size();
+            self.count += 1;
+            let res = unsafe { NonNull::new_unchecked(aligned as *mut u8) };
+            #[cfg(feature = "alloc-log")]
+            eventlog::EVENT_LOG.record(eventlog::EventKind::AllocBytes, layout.size(), layout.align(), res.as_ptr() as usize, 0);
+            return Ok(res);
+        }
+        Err(allocator::AllocError::NoMemory)
     }
 
     fn dealloc(&mut self, pos: core::ptr::NonNull<u8>, layout: core::alloc::Layout) {
+        #[cfg(feature = "alloc-log")]
+        eventlog::EVENT_LOG.record(eventlog::EventKind::DeallocBytes, layout.size(), layout.align(), pos.as_ptr() as usize, 0);
         self.count -= 1;
         if self.count == 0 {
-            self.b_pos = self.start;
+            // 所有字节分配都还完了，把每个region的b_pos都收回到各自的start
+            for region in self.regions[..self.region_count].iter_mut() {
+                region.b_pos = region.start;
+            }
         }
     }
 
     fn total_bytes(&self) -> usize {
-        self.end - self.start
+        self.regions[..self.region_count].iter().map(|r| r.end - r.start).sum()
     }
 
     fn used_bytes(&self) -> usize {
-        self.b_pos - self.start
+        self.regions[..self.region_count].iter().map(|r| r.b_pos - r.start).sum()
     }
 
     fn available_bytes(&self) -> usize {
-        self.p_pos - self.b_pos
+        self.regions[..self.region_count].iter().map(|r| r.p_pos - r.b_pos).sum()
     }
 }
 
@@ -116,23 +182,34 @@ impl<const PAGE_SIZE: usize> PageAllocator for EarlyAllocator<PAGE_SIZE> {
     fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> allocator::AllocResult<usize> {
         let size = num_pages * Self::PAGE_SIZE;
         let align = 1 << align_pow2;
-        self.p_pos = (self.p_pos - size) & !(align - 1);
-        Ok(self.p_pos)
+        for region in self.regions[..self.region_count].iter_mut() {
+            let candidate = (region.p_pos - size) & !(align - 1);
+            if candidate < region.b_pos {
+                continue; // 这个region装不下，换下一个region试
+            }
+            region.p_pos = candidate;
+            #[cfg(feature = "alloc-log")]
+            eventlog::EVENT_LOG.record(eventlog::EventKind::AllocPages, size, align, candidate, 0);
+            return Ok(candidate);
+        }
+        Err(allocator::AllocError::NoMemory)
     }
 
     fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
+        #[cfg(feature = "alloc-log")]
+        eventlog::EVENT_LOG.record(eventlog::EventKind::DeallocPages, num_pages * Self::PAGE_SIZE, 0, pos, 0);
         unimplemented!() // 按上面注释，这个EarlyAllocator，假设页区不会回收
     }
 
     fn total_pages(&self) -> usize {
-        (self.end - self.start) / Self::PAGE_SIZE
+        self.regions[..self.region_count].iter().map(|r| (r.end - r.start) / Self::PAGE_SIZE).sum()
     }
 
     fn used_pages(&self) -> usize {
-        (self.end - self.p_pos) / Self::PAGE_SIZE
+        self.regions[..self.region_count].iter().map(|r| (r.end - r.p_pos) / Self::PAGE_SIZE).sum()
     }
 
     fn available_pages(&self) -> usize {
-        (self.p_pos - self.b_pos) / Self::PAGE_SIZE
+        self.regions[..self.region_count].iter().map(|r| (r.p_pos - r.b_pos) / Self::PAGE_SIZE).sum()
     }
 }
@@ -0,0 +1,117 @@
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use allocator::{AllocResult, ByteAllocator, PageAllocator};
+
+use crate::slab::{SlabByteAllocator, NUM_SIZE_CLASSES};
+
+/// 每个CPU每个size class最多缓存多少个对象。满了就批量还一半回共享slab分配器，
+/// 空了就从共享分配器一个个补(见alloc_on_cpu)。
+const PERCPU_CACHE_CAP: usize = 32;
+/// 简化处理：固定一个CPU数上限，数组放在SmpSlabByteAllocator里，不用动态分配。
+const MAX_CPUS: usize = 8;
+
+/// 一个CPU、一个size class的本地缓存，就是个定长栈，存的是对象地址。
+#[derive(Clone, Copy)]
+struct ArrayCache {
+    objects: [usize; PERCPU_CACHE_CAP],
+    len: usize,
+}
+
+impl ArrayCache {
+    const fn new() -> Self {
+        Self { objects: [0; PERCPU_CACHE_CAP], len: 0 }
+    }
+}
+
+/// 在SlabByteAllocator前面挂一层per-CPU array cache，给每个CPU一份"最近释放的对象"小栈。
+/// 命中本地缓存的alloc/dealloc完全不用碰共享slab分配器，只有缓存miss/满了才落到共享结构上。
+///
+/// 这一层本身不内置锁，所有方法都要`&mut self`：共享slab分配器和所有CPU的缓存在同一个结构里，
+/// 调用者(比如GLOBAL_ALLOCATOR)要把整个SmpSlabByteAllocator放在一把锁后面，命中本地缓存的
+/// 快路径也同样要拿这把锁；省掉的是进共享slab分配器的那一段，不是锁本身。
+pub struct SmpSlabByteAllocator<const PAGE_SIZE: usize, P: PageAllocator> {
+    shared: SlabByteAllocator<PAGE_SIZE, P>,
+    percpu: [[ArrayCache; NUM_SIZE_CLASSES]; MAX_CPUS],
+}
+
+impl<const PAGE_SIZE: usize, P: PageAllocator> SmpSlabByteAllocator<PAGE_SIZE, P> {
+    pub const fn new(shared: SlabByteAllocator<PAGE_SIZE, P>) -> Self {
+        Self {
+            shared,
+            percpu: [[ArrayCache::new(); NUM_SIZE_CLASSES]; MAX_CPUS],
+        }
+    }
+
+    pub fn init(&mut self, start: usize, size: usize) {
+        allocator::BaseAllocator::init(&mut self.shared, start, size);
+    }
+
+    pub fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        allocator::BaseAllocator::add_memory(&mut self.shared, start, size)
+    }
+
+    fn class_index(size: usize, align: usize) -> Option<usize> {
+        SlabByteAllocator::<PAGE_SIZE, P>::class_index(size, align)
+    }
+
+    pub fn alloc_on_cpu(&mut self, cpu_id: usize, layout: Layout) -> AllocResult<NonNull<u8>> {
+        if cpu_id >= MAX_CPUS {
+            return self.shared.alloc(layout);
+        }
+        if let Some(idx) = Self::class_index(layout.size(), layout.align()) {
+            let cache = &mut self.percpu[cpu_id][idx];
+            if cache.len > 0 {
+                cache.len -= 1;
+                let obj = cache.objects[cache.len];
+                return Ok(unsafe { NonNull::new_unchecked(obj as *mut u8) });
+            }
+        }
+        // 本地缓存miss，落到共享的slab分配器上
+        self.shared.alloc(layout)
+    }
+
+    pub fn dealloc_on_cpu(&mut self, cpu_id: usize, pos: NonNull<u8>, layout: Layout) {
+        if cpu_id >= MAX_CPUS {
+            self.shared.dealloc(pos, layout);
+            return;
+        }
+        if let Some(idx) = Self::class_index(layout.size(), layout.align()) {
+            let cache = &mut self.percpu[cpu_id][idx];
+            if cache.len == PERCPU_CACHE_CAP {
+                // 缓存满了，批量还一半给共享分配器，腾出地方，避免每次满了都只还一个
+                let flush_count = PERCPU_CACHE_CAP / 2;
+                for _ in 0..flush_count {
+                    cache.len -= 1;
+                    let obj = cache.objects[cache.len];
+                    let ptr = unsafe { NonNull::new_unchecked(obj as *mut u8) };
+                    self.shared.dealloc(ptr, layout);
+                }
+            }
+            let cache = &mut self.percpu[cpu_id][idx];
+            cache.objects[cache.len] = pos.as_ptr() as usize;
+            cache.len += 1;
+        } else {
+            self.shared.dealloc(pos, layout);
+        }
+    }
+
+    /// 把某个CPU所有size class里缓存的对象都还给共享分配器，迁移/下线这个CPU时用。
+    pub fn flush_cpu_cache(&mut self, cpu_id: usize) {
+        if cpu_id >= MAX_CPUS {
+            return;
+        }
+        for idx in 0..NUM_SIZE_CLASSES {
+            let layout = unsafe {
+                Layout::from_size_align_unchecked(SlabByteAllocator::<PAGE_SIZE, P>::size_of_class(idx), 1)
+            };
+            let cache = &mut self.percpu[cpu_id][idx];
+            while cache.len > 0 {
+                cache.len -= 1;
+                let obj = cache.objects[cache.len];
+                let ptr = unsafe { NonNull::new_unchecked(obj as *mut u8) };
+                self.shared.dealloc(ptr, layout);
+            }
+        }
+    }
+}
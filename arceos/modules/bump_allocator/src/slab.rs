@@ -0,0 +1,247 @@
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+
+/// 按2的幂划分的size class，最小8字节(要放得下内嵌的"下一个空闲对象"指针，即一个usize)，
+/// 最大到一页。对象实际大小/对齐需求会被向上取整到这些class之一。
+const SIZE_CLASSES: [usize; 10] = [8, 16, 32, 64, 128, 256, 512, 1024, 2048, 4096];
+pub(crate) const NUM_SIZE_CLASSES: usize = SIZE_CLASSES.len();
+
+/// 每个slab(一页)开头放的头部，剩下的空间才切成一个个固定大小的对象。
+/// free_list是本slab内部空闲对象链表的表头，链表节点就是对象本身：把"下一个空闲对象的地址"
+/// 直接写在这个空闲对象的前8字节里，不需要额外的位图/数组来记录，这也是slab分配器"embedded free
+/// list"的经典做法。
+/// prev/next把本slab串在它所属size class的"还有空闲对象的slab"双向链表里，这样free()时
+/// 如果某个slab从"满"变成"有空闲"，或者从"有空闲"变成"全空闲可以还给页分配器"，都能O(1)地
+/// 从链表里摘除/插入，不需要遍历。
+#[repr(C)]
+struct SlabHeader {
+    free_list: usize,
+    free_count: usize,
+    capacity: usize,
+    prev: usize,
+    next: usize,
+}
+
+/// 把一个class的"还有空闲对象的slab"组织成双向链表，表头记在这里。0表示链表为空/到头。
+#[derive(Clone, Copy)]
+struct SizeClass {
+    partial_slabs: usize,
+}
+
+/// kmem_cache风格的slab字节分配器：按size class维护若干slab(从P这个PageAllocator按页申请)，
+/// 每个slab内部切成定长对象，靠嵌入式空闲链表做O(1)的alloc/dealloc，而不是像EarlyAllocator
+/// 那样只能靠一个全局count决定什么时候整体回收。
+///
+/// P负责提供/回收实际的页内存，这样SlabByteAllocator可以和任意PageAllocator组合使用
+/// （比如本crate里新增的BuddyPageAllocator）。
+pub struct SlabByteAllocator<const PAGE_SIZE: usize, P: PageAllocator> {
+    page_alloc: P,
+    classes: [SizeClass; NUM_SIZE_CLASSES],
+    used_bytes: usize,
+}
+
+impl<const PAGE_SIZE: usize, P: PageAllocator> SlabByteAllocator<PAGE_SIZE, P> {
+    pub const fn new(page_alloc: P) -> Self {
+        Self {
+            page_alloc,
+            classes: [SizeClass { partial_slabs: 0 }; NUM_SIZE_CLASSES],
+            used_bytes: 0,
+        }
+    }
+
+    /// 把申请的(size, align)向上取整到某个size class的下标，取不到(比一页还大)就返回None。
+    pub(crate) fn class_index(size: usize, align: usize) -> Option<usize> {
+        let need = size.max(align).max(SIZE_CLASSES[0]);
+        SIZE_CLASSES.iter().position(|&c| c >= need)
+    }
+
+    /// idx对应的size class实际大小，给percpu cache批量归还时重建Layout用。
+    pub(crate) fn size_of_class(idx: usize) -> usize {
+        SIZE_CLASSES[idx]
+    }
+
+    unsafe fn header_of(slab_addr: usize) -> *mut SlabHeader {
+        slab_addr as *mut SlabHeader
+    }
+
+    /// 把slab从它所在class的partial链表里摘掉(slab要么变满了，要么变成整页空闲要还给page_alloc)
+    unsafe fn unlink(&mut self, idx: usize, slab_addr: usize) {
+        let header = &mut *Self::header_of(slab_addr);
+        if header.prev != 0 {
+            (*Self::header_of(header.prev)).next = header.next;
+        } else {
+            self.classes[idx].partial_slabs = header.next;
+        }
+        if header.next != 0 {
+            (*Self::header_of(header.next)).prev = header.prev;
+        }
+    }
+
+    /// 把slab插到它所在class的partial链表头部(新申请的slab，或者刚从"满"变成"有空闲"的slab)
+    unsafe fn link_front(&mut self, idx: usize, slab_addr: usize) {
+        let old_head = self.classes[idx].partial_slabs;
+        let header = &mut *Self::header_of(slab_addr);
+        header.prev = 0;
+        header.next = old_head;
+        if old_head != 0 {
+            (*Self::header_of(old_head)).prev = slab_addr;
+        }
+        self.classes[idx].partial_slabs = slab_addr;
+    }
+
+    /// 从page_alloc要一页来给idx这个class新建一个slab，切好对象并挂到partial链表上
+    fn grow(&mut self, idx: usize) -> AllocResult<()> {
+        let slab_addr = self.page_alloc.alloc_pages(1, PAGE_SIZE.trailing_zeros() as usize)?;
+        let class_size = SIZE_CLASSES[idx];
+        let header_size = core::mem::size_of::<SlabHeader>();
+        // 头部本身也占用对象空间，按class_size对齐着走：开头header_slots个对象的位置
+        // (SlabHeader的大小按class_size向上取整)留给头部，对象从这之后开始切
+        let header_slots = header_size.div_ceil(class_size);
+        let capacity = PAGE_SIZE / class_size - header_slots;
+        unsafe {
+            let header = &mut *Self::header_of(slab_addr);
+            header.capacity = capacity;
+            header.free_count = capacity;
+            header.free_list = 0;
+            let objects_base = slab_addr + header_slots * class_size;
+            for i in 0..capacity {
+                let obj = objects_base + i * class_size;
+                *(obj as *mut usize) = header.free_list;
+                header.free_list = obj;
+            }
+            self.link_front(idx, slab_addr);
+        }
+        Ok(())
+    }
+}
+
+impl<const PAGE_SIZE: usize, P: PageAllocator> BaseAllocator for SlabByteAllocator<PAGE_SIZE, P> {
+    fn init(&mut self, start: usize, size: usize) {
+        self.page_alloc.init(start, size);
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        self.page_alloc.add_memory(start, size)
+    }
+}
+
+impl<const PAGE_SIZE: usize, P: PageAllocator> ByteAllocator for SlabByteAllocator<PAGE_SIZE, P> {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let idx = Self::class_index(layout.size(), layout.align()).ok_or(AllocError::InvalidParam)?;
+        if SIZE_CLASSES[idx] == PAGE_SIZE {
+            // 整页大小的class放不下一个SlabHeader还要再切出至少一个对象(header_slots就把
+            // capacity吃成0了)，所以干脆不在页里放header：一页就是一个对象，直接问page_alloc
+            // 要/还一整页，复用交给下面的PageAllocator自己做(比如BuddyPageAllocator的合并)。
+            let addr = self.page_alloc.alloc_pages(1, PAGE_SIZE.trailing_zeros() as usize)?;
+            self.used_bytes += SIZE_CLASSES[idx];
+            return Ok(unsafe { NonNull::new_unchecked(addr as *mut u8) });
+        }
+        if self.classes[idx].partial_slabs == 0 {
+            self.grow(idx)?;
+        }
+        let slab_addr = self.classes[idx].partial_slabs;
+        unsafe {
+            let header = &mut *Self::header_of(slab_addr);
+            let obj = header.free_list;
+            header.free_list = *(obj as *const usize);
+            header.free_count -= 1;
+            if header.free_count == 0 {
+                self.unlink(idx, slab_addr);
+            }
+            self.used_bytes += SIZE_CLASSES[idx];
+            Ok(NonNull::new_unchecked(obj as *mut u8))
+        }
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        let idx = Self::class_index(layout.size(), layout.align()).expect("invalid layout passed to dealloc");
+        if SIZE_CLASSES[idx] == PAGE_SIZE {
+            self.page_alloc.dealloc_pages(pos.as_ptr() as usize, 1);
+            self.used_bytes -= SIZE_CLASSES[idx];
+            return;
+        }
+        let slab_addr = (pos.as_ptr() as usize) & !(PAGE_SIZE - 1);
+        unsafe {
+            let header = &mut *Self::header_of(slab_addr);
+            let was_full = header.free_count == 0;
+            let obj = pos.as_ptr() as usize;
+            *(obj as *mut usize) = header.free_list;
+            header.free_list = obj;
+            header.free_count += 1;
+            self.used_bytes -= SIZE_CLASSES[idx];
+            if was_full {
+                self.link_front(idx, slab_addr);
+            } else if header.free_count == header.capacity {
+                // 整页都空了，还给page allocator，避免长期占着没用到的页
+                self.unlink(idx, slab_addr);
+                self.page_alloc.dealloc_pages(slab_addr, 1);
+            }
+        }
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.page_alloc.total_pages() * PAGE_SIZE
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.page_alloc.available_pages() * PAGE_SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buddy::BuddyPageAllocator;
+
+    const PAGE_SIZE: usize = 4096;
+    type TestAllocator = SlabByteAllocator<PAGE_SIZE, BuddyPageAllocator<PAGE_SIZE, 8, 256>>;
+
+    fn new_allocator(mem: &mut [u8]) -> TestAllocator {
+        let mut a = TestAllocator::new(BuddyPageAllocator::new());
+        a.init(mem.as_mut_ptr() as usize, mem.len());
+        a
+    }
+
+    /// dealloc靠把对象地址按PAGE_SIZE向下取整来找slab头，所以交给分配器的内存要按页对齐；
+    /// 栈上的[u8; N]只保证1字节对齐。
+    #[repr(C, align(4096))]
+    struct PageAlignedMem([u8; 16 * PAGE_SIZE]);
+
+    #[test]
+    fn small_object_alloc_dealloc_reuse_roundtrip() {
+        let mut mem = PageAlignedMem([0u8; 16 * PAGE_SIZE]);
+        let mut a = new_allocator(&mut mem.0);
+        let layout = Layout::from_size_align(16, 8).unwrap();
+
+        let p1 = a.alloc(layout).unwrap();
+        assert_eq!(a.used_bytes(), 16);
+        a.dealloc(p1, layout);
+        assert_eq!(a.used_bytes(), 0);
+
+        // p1是slab里唯一分配出去的对象，释放后整个slab全空、页还给了页分配器；下一次同size class
+        // 的分配重新要到同一页、切出同样的空闲链表，所以拿到的还是p1这个地址
+        let p2 = a.alloc(layout).unwrap();
+        assert_eq!(p1, p2);
+        a.dealloc(p2, layout);
+    }
+
+    /// 整页大小的size class不走SlabHeader那一套，直接问page_alloc要/还一整页，
+    /// 这条路径之前有过capacity算成0的bug，这里守住一个基本的alloc/dealloc往返。
+    #[test]
+    fn page_sized_class_alloc_dealloc_roundtrip() {
+        let mut mem = [0u8; 16 * PAGE_SIZE];
+        let mut a = new_allocator(&mut mem);
+        let layout = Layout::from_size_align(PAGE_SIZE, PAGE_SIZE).unwrap();
+
+        let p = a.alloc(layout).unwrap();
+        assert_eq!(a.used_bytes(), PAGE_SIZE);
+        a.dealloc(p, layout);
+        assert_eq!(a.used_bytes(), 0);
+    }
+}
@@ -3,7 +3,9 @@ use alloc::vec::Vec;
 use axhal::misc::random;
 use core::hash::{Hash, Hasher};
 
-// 写一个简化的HashMap，只完成arceos/exercises/support_hashmap/src/main.rs中用到的功能
+// 写一个简化的HashMap，最初只完成arceos/exercises/support_hashmap/src/main.rs中用到的insert/size/iter，
+// 后来补上了get/get_mut/remove/contains_key/clear/entry，以及IntoIterator/keys/values，
+// 让它能多少顶一下std::collections::HashMap的常见用法，不至于事事都要iter()手写线性扫描。
 pub struct HashMap<K, V> {
     seed: u64,
     buckets: Vec<Vec<(K, V)>>,
@@ -121,10 +123,273 @@ where K: Hash + Eq + Clone, V: Clone
         let old = core::mem::take(&mut self.buckets);
         self.bucket_count *= 2;
         self.buckets = vec![Vec::new(); self.bucket_count];
+        // old里的每个key都已经在size里数过一次了，insert()还会再+=1，这里先清零避免重复计数
+        self.size = 0;
         for old_bucket in old {
             for (key, value) in old_bucket {
                 self.insert(key, value);
             }
         }
     }
+
+    /// 和rehash()相反，负载降得足够低时把self.buckets缩小一半，避免remove()之后一直占着一开始
+    /// 扩容出来的大数组。bucket_count不缩到16以下，16是new()里的初始大小。
+    fn shrink(&mut self) {
+        let old = core::mem::take(&mut self.buckets);
+        self.bucket_count /= 2;
+        self.buckets = vec![Vec::new(); self.bucket_count];
+        // 和rehash()一样，重新insert()之前要先把size清零，否则每个已有元素都会被多算一次
+        self.size = 0;
+        for old_bucket in old {
+            for (key, value) in old_bucket {
+                self.insert(key, value);
+            }
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let index = self.hash(key);
+        self.buckets[index as usize].iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let index = self.hash(key);
+        self.buckets[index as usize].iter_mut().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// 把key对应的entry删掉，返回被删的value。用swap_remove代替remove，省得搬动桶里剩下的元素
+    /// (HashMap本来也不保证bucket内部的顺序)
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let index = self.hash(key) as usize;
+        let bucket = &mut self.buckets[index];
+        let pos = bucket.iter().position(|(k, _)| k == key)?;
+        let (_, value) = bucket.swap_remove(pos);
+        self.size -= 1;
+        // 负载降得太低就收缩一下，和insert()里超过阈值就扩容是对称的逻辑
+        if self.bucket_count > 16 && self.size < self.bucket_count / 4 {
+            self.shrink();
+        }
+        Some(value)
+    }
+
+    pub fn clear(&mut self) {
+        for bucket in self.buckets.iter_mut() {
+            bucket.clear();
+        }
+        self.size = 0;
+    }
+
+    /// entry API：先按key算好是Occupied(已经有这个key，记下它在哪个bucket哪个位置)还是
+    /// Vacant(还没有这个key)，这样or_insert/or_insert_with就只需要一次查找，不用先get()判断
+    /// 有没有，没有再insert()一次
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        let index = self.hash(&key) as usize;
+        match self.buckets[index].iter().position(|(k, _)| *k == key) {
+            Some(slot) => Entry::Occupied { map: self, index, slot },
+            None => Entry::Vacant { map: self, key, index },
+        }
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.iter() }
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.iter() }
+    }
+}
+
+pub enum Entry<'a, K, V> {
+    Occupied { map: &'a mut HashMap<K, V>, index: usize, slot: usize },
+    Vacant { map: &'a mut HashMap<K, V>, key: K, index: usize },
+}
+
+impl<'a, K, V> Entry<'a, K, V>
+where K: Hash + Eq + Clone, V: Clone
+{
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied { map, index, slot } => &mut map.buckets[index][slot].1,
+            Entry::Vacant { map, key, index } => Self::insert_vacant(map, key, index, default),
+        }
+    }
+
+    pub fn or_insert_with<F>(self, default: F) -> &'a mut V
+    where F: FnOnce() -> V
+    {
+        match self {
+            Entry::Occupied { map, index, slot } => &mut map.buckets[index][slot].1,
+            Entry::Vacant { map, key, index } => {
+                let value = default();
+                Self::insert_vacant(map, key, index, value)
+            }
+        }
+    }
+
+    /// entry()已经确认过index这个bucket里没有key了，这里直接push进去，不用再走一遍
+    /// insert()里"bucket里找key"的那次线性扫描；bucket_count超过阈值需要rehash()时，
+    /// bucket位置会变，才退化成再查一次(rehash本身就不是每次插入都会碰上的路径)。
+    fn insert_vacant(map: &'a mut HashMap<K, V>, key: K, index: usize, value: V) -> &'a mut V {
+        map.buckets[index].push((key, value));
+        map.size += 1;
+        if map.size > map.bucket_count * 2 {
+            let key_for_lookup = map.buckets[index].last().unwrap().0.clone();
+            map.rehash();
+            return map.get_mut(&key_for_lookup).expect("key was just inserted");
+        }
+        let bucket = &mut map.buckets[index];
+        let last = bucket.len() - 1;
+        &mut bucket[last].1
+    }
+}
+
+pub struct Keys<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V>
+where K: Clone, V: Clone
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, _)| k)
+    }
+}
+
+pub struct Values<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V>
+where K: Clone, V: Clone
+{
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, v)| v)
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a HashMap<K, V>
+where K: Hash + Eq + Clone, V: Clone
+{
+    type Item = &'a (K, V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// 拿到所有权遍历用的迭代器，把buckets这个二维Vec拍平成一个个(K, V)
+pub struct IntoIter<K, V> {
+    buckets: vec::IntoIter<Vec<(K, V)>>,
+    current: vec::IntoIter<(K, V)>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.current.next() {
+                return Some(item);
+            }
+            self.current = self.buckets.next()?.into_iter();
+        }
+    }
+}
+
+impl<K, V> IntoIterator for HashMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let mut buckets = self.buckets.into_iter();
+        let current = buckets.next().unwrap_or_default().into_iter();
+        IntoIter { buckets, current }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+
+    #[test]
+    fn insert_get_remove_roundtrip_tracks_size() {
+        let mut map = HashMap::new();
+        assert_eq!(map.size(), 0);
+
+        map.insert("a", 1);
+        map.insert("b", 2);
+        assert_eq!(map.size(), 2);
+        assert_eq!(map.get(&"a"), Some(&1));
+        assert_eq!(map.get(&"b"), Some(&2));
+        assert_eq!(map.get(&"c"), None);
+
+        // 同一个key再insert一次是更新value，不应该再让size增加
+        map.insert("a", 10);
+        assert_eq!(map.size(), 2);
+        assert_eq!(map.get(&"a"), Some(&10));
+
+        assert_eq!(map.remove(&"a"), Some(10));
+        assert_eq!(map.size(), 1);
+        assert_eq!(map.get(&"a"), None);
+        assert_eq!(map.remove(&"a"), None);
+    }
+
+    #[test]
+    fn entry_or_insert_is_idempotent() {
+        let mut map = HashMap::new();
+        *map.entry("count").or_insert(0) += 1;
+        *map.entry("count").or_insert(0) += 1;
+        assert_eq!(map.get(&"count"), Some(&2));
+
+        let called = core::cell::Cell::new(0);
+        map.entry("count").or_insert_with(|| {
+            called.set(called.get() + 1);
+            100
+        });
+        // key已经存在，or_insert_with的闭包不应该被调用
+        assert_eq!(called.get(), 0);
+        assert_eq!(map.get(&"count"), Some(&2));
+    }
+
+    /// 反复插入/删除触发多次rehash()/shrink()，size()必须始终等于实际还在map里的key数，
+    /// 这是之前"忘记在重插入前清零size"那个bug的回归测试。
+    #[test]
+    fn rehash_and_shrink_keep_size_accurate() {
+        let mut map = HashMap::new();
+        for i in 0..200 {
+            map.insert(i, i * 2);
+        }
+        assert_eq!(map.size(), 200);
+        // 200个key时bucket_count是128；删到31个时缩到64，删到15个时缩到32
+        for i in 0..190 {
+            assert_eq!(map.remove(&i), Some(i * 2));
+        }
+        assert_eq!(map.size(), 10);
+        for i in 190..200 {
+            assert_eq!(map.get(&i), Some(&(i * 2)));
+        }
+    }
+
+    #[test]
+    fn into_iter_yields_every_inserted_pair() {
+        let mut map = HashMap::new();
+        for i in 0..20 {
+            map.insert(i, i.to_string());
+        }
+        let mut collected: Vec<(i32, String)> = map.into_iter().collect();
+        collected.sort();
+        let expected: Vec<(i32, String)> = (0..20).map(|i| (i, i.to_string())).collect();
+        assert_eq!(collected, expected);
+    }
 }
\ No newline at end of file